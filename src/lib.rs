@@ -3,19 +3,24 @@
 //! The algorithm replaces or deletes characters from the input stream using
 //! various filters that are applied in the following sequential order:
 //!
+//! 0. Strip a leading BOM and normalize `\r`, `\r\n` and `\n` line endings.
 //! 1. Replace all whitespace with space.
 //! 2. Filter all control characters.
-//! 3. `REPLACE_ORIG_WITH_UNDERSCORE`
-//! 4. `REPLACE_ORIG_WITH_SPACE`
-//! 5. `FILTER_PROCESSED_AFTER_LAST_PROCESSED_WAS_SPACE`
-//! 6. `FILTER_PROCESSED_AFTER_LAST_PROCESSED_WAS_UNDERSCORE`
-//! 7. `FILTER_ORIG_AFTER_LAST_PROCESSED_WAS_WHITESPACE`
-//! 8. `FILTER_ORIG_NON_PRINTING_CHARS`
-//! 9. `TRIM_LINE`
-//! 10. `INSERT_LINE_SEPARATOR`
+//! 3. `replace_orig_with_underscore`
+//! 4. `replace_orig_with_space`
+//! 5. `filter_processed_after_last_processed_was_space`
+//! 6. `filter_processed_after_last_processed_was_underscore`
+//! 7. `filter_orig_after_last_processed_was_whitespace`
+//! 8. `filter_orig_non_printing_chars`
+//! 9. `trim_line`
+//! 10. `insert_line_separator`
 //! 11. `TRIM_END_LINES`
+//! 12. `handle_reserved_names`
+//! 13. `slugify` (opt-in)
+//! 14. `max_len` (opt-in)
 //!
-//! For details see the definition and documentation of the above (private) constants.
+//! For details see the definition and documentation of the above
+//! [`Sanitizer`] fields.
 //!
 //! # Rationale
 //!
@@ -35,7 +40,23 @@
 //! assert_eq!(output, "Read_ http_blog.getreu.net_projects_tp-note");
 //! ```
 //! The output string's length is guaranteed to be shorter or equal than the input
-//! string's length.
+//! string's length, except that each line whose stem is a Windows reserved
+//! device name gains a `_` prefix (see [`Sanitizer::handle_reserved_names`]).
+//!
+//! # Custom configuration
+//!
+//! The character sets above are only the default configuration. Some callers
+//! need a different trade-off, e.g. someone targeting strictly FAT32 wants
+//! `+,;=[]` removed, while someone targeting URLs wants a different set
+//! replaced. Use [`Sanitizer`] to build a custom configuration:
+//!
+//! ```
+//! use sanitize_filename_reader_friendly::Sanitizer;
+//! let output = Sanitizer::new()
+//!     .replace_with_underscore(":\\/|?~+,;=[]")
+//!     .sanitize("FAT32[1]:name");
+//! assert_eq!(output, "FAT32_1_name");
+//! ```
 
 /// Start value for the algorithm. We pretend the last was just a regular letter
 /// to which no `LAST_PROCESSED_WAS` rule applies.
@@ -78,66 +99,680 @@ const TRIM_LINE: &str = "_-.,;";
 /// Insert the character below between lines.
 const INSERT_LINE_SEPARATOR: char = '-';
 
-/// Converts strings in a file system friendly and human readable form.
-pub fn sanitize(s: &str) -> String {
-    // This is used in a closure later.
-    let mut last_processed_chr = LAST_PROCESSED_START_CHAR;
-
-    // Proceed line by line.
-    s.lines()
-        .map(|l| {
-            let mut s = l
-                .chars()
+/// Internal-only marker used to join lines while the whole-string trim
+/// logic runs, so that real line boundaries can later be told apart from
+/// an ordinary, unfiltered occurrence of `insert_line_separator` inside a
+/// line's own content. Control characters are always filtered out of line
+/// content in the character loop above, so this sentinel can never
+/// collide with real content.
+const LINE_SEPARATOR_SENTINEL: char = '\0';
+
+/// By default, rewrite a line whose stem (the part before the first `.`)
+/// is a Windows reserved device name, case-insensitively: `CON`, `PRN`,
+/// `AUX`, `NUL`, `COM0`-`COM9`, `LPT0`-`LPT9`.
+const HANDLE_RESERVED_NAMES: bool = true;
+
+/// Returns `true` if `stem` is a Windows reserved device name, compared
+/// case-insensitively, e.g. `CON`, `PRN`, `AUX`, `NUL`, `COM0`-`COM9` or
+/// `LPT0`-`LPT9`.
+///
+/// Mirrors the regular expression noted in earlier versions of this crate:
+/// `^(con|prn|aux|nul|com[0-9]|lpt[0-9])(\..*)?$`, applied to the part of
+/// the name before the first `.`.
+fn is_reserved_device_name(stem: &str) -> bool {
+    let lower = stem.to_ascii_lowercase();
+    matches!(lower.as_str(), "con" | "prn" | "aux" | "nul")
+        || ((lower.starts_with("com") || lower.starts_with("lpt"))
+            && lower.len() == 4
+            && lower.as_bytes()[3].is_ascii_digit())
+}
+
+/// By default, slug mode is disabled: the crate intentionally preserves
+/// Unicode unless a caller opts in.
+const SLUGIFY: bool = false;
+
+/// Maps a lower-cased accented Latin character to its ASCII transliteration,
+/// or `None` if `c` has no known transliteration and should be dropped.
+///
+/// Covers the common Latin-1 Supplement and Latin Extended-A letters, in the
+/// style of the WordPress `sanitize_title_with_dashes` transliteration table.
+fn transliterate(c: char) -> Option<&'static str> {
+    Some(match c {
+        'à' | 'á' | 'â' | 'ã' | 'ä' | 'å' | 'ā' | 'ă' | 'ą' => "a",
+        'æ' => "ae",
+        'ç' | 'ć' | 'č' => "c",
+        'ð' => "d",
+        'è' | 'é' | 'ê' | 'ë' | 'ē' | 'ĕ' | 'ė' | 'ę' | 'ě' => "e",
+        'ì' | 'í' | 'î' | 'ï' | 'ī' | 'ĭ' | 'į' => "i",
+        'ñ' | 'ń' | 'ň' => "n",
+        'ò' | 'ó' | 'ô' | 'õ' | 'ö' | 'ø' | 'ō' => "o",
+        'œ' => "oe",
+        'ś' | 'š' => "s",
+        'ß' => "ss",
+        'ù' | 'ú' | 'û' | 'ü' | 'ū' | 'ŭ' | 'ů' => "u",
+        'ý' | 'ÿ' => "y",
+        'ź' | 'ż' | 'ž' => "z",
+        _ => return None,
+    })
+}
+
+/// Strips a leading UTF-8 BOM and normalizes `\r\n` and lone `\r` (old Mac)
+/// line endings to `\n`, so the per-line processing below sees consistent
+/// input regardless of which OS the input came from.
+fn normalize_newlines(s: &str) -> String {
+    let s = s.strip_prefix('\u{feff}').unwrap_or(s);
+    let mut out = String::with_capacity(s.len());
+    let mut chars = s.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c == '\r' {
+            if chars.peek() == Some(&'\n') {
+                chars.next();
+            }
+            out.push('\n');
+        } else {
+            out.push(c);
+        }
+    }
+    out
+}
+
+/// Returns `true` if `c` is a combining mark, i.e. it is meant to be
+/// rendered attached to the preceding character and must not become the
+/// first character after a truncation point.
+fn is_combining_mark(c: char) -> bool {
+    matches!(c,
+        '\u{0300}'..='\u{036F}'
+        | '\u{1AB0}'..='\u{1AFF}'
+        | '\u{1DC0}'..='\u{1DFF}'
+        | '\u{20D0}'..='\u{20FF}'
+        | '\u{FE20}'..='\u{FE2F}')
+}
+
+/// Suffixes after the last `.` longer than this are not considered a
+/// file extension worth preserving when applying `max_len`.
+const MAX_EXTENSION_BYTES: usize = 16;
+
+/// Truncates `s` to at most `max_bytes` bytes without splitting a multi-byte
+/// UTF-8 sequence or separating a combining mark from its base character.
+fn truncate_to_byte_boundary(s: &str, max_bytes: usize) -> &str {
+    if s.len() <= max_bytes {
+        return s;
+    }
+    let mut end = max_bytes;
+    while end > 0 && !s.is_char_boundary(end) {
+        end -= 1;
+    }
+    while end > 0 && s[end..].chars().next().is_some_and(is_combining_mark) {
+        end -= 1;
+        while end > 0 && !s.is_char_boundary(end) {
+            end -= 1;
+        }
+    }
+    &s[..end]
+}
+
+/// Truncates `s` to at most `max_len` bytes, preserving a short
+/// extension-like suffix after the last `.` if there is one, and re-trims
+/// the cut end by `trim_line` so the result never ends on `_-.,;` or
+/// whitespace.
+fn apply_max_len(s: &str, max_len: usize, trim_line: &str) -> String {
+    if s.len() <= max_len {
+        return s.to_string();
+    }
+    let trim_end = |stem: &str| {
+        stem.trim_end_matches(|c: char| c.is_whitespace() || trim_line.find(c).is_some())
+            .to_string()
+    };
+    if let Some(dot) = s.rfind('.') {
+        let ext = &s[dot..];
+        if ext.len() < max_len && ext.len() <= MAX_EXTENSION_BYTES {
+            let stem = truncate_to_byte_boundary(&s[..dot], max_len - ext.len());
+            return trim_end(stem) + ext;
+        }
+    }
+    trim_end(truncate_to_byte_boundary(s, max_len))
+}
+
+/// Like [`normalize_newlines`], but pairs every output `char` with the byte
+/// offset in the original `s` it came from. A `\r\n` or lone `\r` collapses
+/// into a single `\n` carrying the offset of its first byte.
+fn normalize_newlines_with_map(s: &str) -> Vec<(usize, char)> {
+    let skip = s.len() - s.strip_prefix('\u{feff}').unwrap_or(s).len();
+    let body = &s[skip..];
+    let mut out = Vec::with_capacity(body.len());
+    let mut chars = body.char_indices().peekable();
+    while let Some((idx, c)) = chars.next() {
+        let origin = idx + skip;
+        if c == '\r' {
+            if let Some(&(_, '\n')) = chars.peek() {
+                chars.next();
+            }
+            out.push((origin, '\n'));
+        } else {
+            out.push((origin, c));
+        }
+    }
+    out
+}
+
+/// Splits `chars` at `\n` markers into lines, dropping the markers. Returns
+/// one `(line, separator_origin)` pair per line, where `separator_origin` is
+/// the byte offset the line's trailing `\n` came from, or `input_len` for
+/// the last line, which has none.
+fn split_into_lines_with_map(
+    chars: Vec<(usize, char)>,
+    input_len: usize,
+) -> Vec<(Vec<(usize, char)>, usize)> {
+    let mut lines = Vec::new();
+    let mut current = Vec::new();
+    for (origin, c) in chars {
+        if c == '\n' {
+            lines.push((std::mem::take(&mut current), origin));
+        } else {
+            current.push((origin, c));
+        }
+    }
+    lines.push((current, input_len));
+    lines
+}
+
+/// Drops leading and trailing entries whose `char` would be trimmed by
+/// `trim_line`, keeping the map aligned with the trimmed output. `sentinel`,
+/// if given, is a `(char, is_trimmed)` pair treated as trimmable exactly
+/// when `is_trimmed` is `true`, regardless of `trim_line` or whitespace —
+/// used to trim [`LINE_SEPARATOR_SENTINEL`] markers the same way the real
+/// `insert_line_separator` they stand in for would be trimmed.
+fn trim_map_entries(
+    entries: &mut Vec<(usize, char)>,
+    trim_line: &str,
+    sentinel: Option<(char, bool)>,
+) {
+    let is_trimmed = |c: char| match sentinel {
+        Some((sep, sep_is_trimmed)) if c == sep => sep_is_trimmed,
+        _ => c.is_whitespace() || trim_line.find(c).is_some(),
+    };
+    while entries.first().is_some_and(|&(_, c)| is_trimmed(c)) {
+        entries.remove(0);
+    }
+    while entries.last().is_some_and(|&(_, c)| is_trimmed(c)) {
+        entries.pop();
+    }
+}
+
+/// Rewrites segments between [`LINE_SEPARATOR_SENTINEL`] markers whose stem
+/// is a Windows reserved device name, the map-aware counterpart of the
+/// reserved-name step in [`Sanitizer::sanitize`]. The synthetic `_` prefix
+/// maps to the origin of the first character of its segment. Operating on
+/// the sentinel rather than the real, configurable `insert_line_separator`
+/// avoids mis-splitting a line whose own content happens to contain that
+/// separator character.
+fn apply_reserved_names_entries(entries: Vec<(usize, char)>) -> Vec<(usize, char)> {
+    let mut result = Vec::with_capacity(entries.len() + 1);
+    let mut segment: Vec<(usize, char)> = Vec::new();
+    for (origin, c) in entries {
+        if c == LINE_SEPARATOR_SENTINEL {
+            append_reserved_name_checked_segment(&mut segment, &mut result);
+            result.push((origin, c));
+        } else {
+            segment.push((origin, c));
+        }
+    }
+    append_reserved_name_checked_segment(&mut segment, &mut result);
+    result
+}
+
+fn append_reserved_name_checked_segment(
+    segment: &mut Vec<(usize, char)>,
+    result: &mut Vec<(usize, char)>,
+) {
+    let stem: String = segment
+        .iter()
+        .take_while(|&&(_, c)| c != '.')
+        .map(|&(_, c)| c)
+        .collect();
+    if is_reserved_device_name(&stem) {
+        if let Some(&(origin, _)) = segment.first() {
+            result.push((origin, '_'));
+        }
+    }
+    result.append(segment);
+}
+
+/// The map-aware counterpart of [`truncate_to_byte_boundary`]: returns how
+/// many leading `entries` fit within `max_bytes` without splitting a
+/// multi-byte sequence or a combining sequence.
+fn truncate_entries_to_byte_boundary(entries: &[(usize, char)], max_bytes: usize) -> usize {
+    let mut bytes = 0;
+    let mut keep = 0;
+    for &(_, c) in entries {
+        let len = c.len_utf8();
+        if bytes + len > max_bytes {
+            break;
+        }
+        bytes += len;
+        keep += 1;
+    }
+    while keep > 0 && entries.get(keep).is_some_and(|&(_, c)| is_combining_mark(c)) {
+        keep -= 1;
+    }
+    keep
+}
+
+/// The map-aware counterpart of [`apply_max_len`].
+fn apply_max_len_entries(
+    entries: Vec<(usize, char)>,
+    max_len: usize,
+    trim_line: &str,
+) -> Vec<(usize, char)> {
+    let total_bytes: usize = entries.iter().map(|&(_, c)| c.len_utf8()).sum();
+    if total_bytes <= max_len {
+        return entries;
+    }
+    let trim_end = |v: &mut Vec<(usize, char)>| {
+        let is_trimmed = |c: char| c.is_whitespace() || trim_line.find(c).is_some();
+        while v.last().is_some_and(|&(_, c)| is_trimmed(c)) {
+            v.pop();
+        }
+    };
+    if let Some(dot_i) = entries.iter().rposition(|&(_, c)| c == '.') {
+        let ext_bytes: usize = entries[dot_i..].iter().map(|&(_, c)| c.len_utf8()).sum();
+        if ext_bytes < max_len && ext_bytes <= MAX_EXTENSION_BYTES {
+            let keep = truncate_entries_to_byte_boundary(&entries[..dot_i], max_len - ext_bytes);
+            let mut stem = entries[..keep].to_vec();
+            trim_end(&mut stem);
+            stem.extend_from_slice(&entries[dot_i..]);
+            return stem;
+        }
+    }
+    let keep = truncate_entries_to_byte_boundary(&entries, max_len);
+    let mut out = entries[..keep].to_vec();
+    trim_end(&mut out);
+    out
+}
+
+/// Turns `s` into an ASCII URL-path-segment slug: lower-cases, transliterates
+/// accented Latin characters to ASCII, maps spaces and underscores to a
+/// dash, collapses runs of dashes into one, and trims leading/trailing
+/// dashes. Any other character outside `[a-z0-9-]` without a
+/// transliteration is dropped.
+fn to_slug(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for c in s.to_lowercase().chars() {
+        if c.is_ascii_alphanumeric() {
+            out.push(c);
+        } else if c == ' ' || c == '_' || c == '-' {
+            out.push('-');
+        } else if let Some(ascii) = transliterate(c) {
+            out.push_str(ascii);
+        }
+        // Drop anything else, e.g. punctuation with no ASCII equivalent.
+    }
+
+    let mut slug = String::with_capacity(out.len());
+    let mut last_was_dash = false;
+    for c in out.chars() {
+        if c == '-' {
+            if !last_was_dash {
+                slug.push('-');
+            }
+            last_was_dash = true;
+        } else {
+            slug.push(c);
+            last_was_dash = false;
+        }
+    }
+    slug.trim_matches('-').to_string()
+}
+
+/// Configurable sanitizer.
+///
+/// [`sanitize()`] is a convenience wrapper around `Sanitizer::new().sanitize()`
+/// using the default character sets described in the crate documentation.
+/// Build a [`Sanitizer`] directly to customize them, e.g. to keep or drop
+/// characters relevant to a particular file system or use case.
+///
+/// ```
+/// use sanitize_filename_reader_friendly::Sanitizer;
+/// let output = Sanitizer::new()
+///     .replace_with_space("_")
+///     .sanitize("abc_efg");
+/// assert_eq!(output, "abc efg");
+/// ```
+#[derive(Debug, Clone)]
+pub struct Sanitizer {
+    replace_orig_with_underscore: String,
+    replace_orig_with_space: String,
+    filter_processed_after_last_processed_was_space: String,
+    filter_processed_after_last_processed_was_underscore: String,
+    filter_orig_after_last_processed_was_whitespace: String,
+    filter_orig_non_printing_chars: String,
+    trim_line: String,
+    handle_reserved_names: bool,
+    insert_line_separator: char,
+    slugify: bool,
+    max_len: Option<usize>,
+}
+
+impl Default for Sanitizer {
+    fn default() -> Self {
+        Sanitizer {
+            replace_orig_with_underscore: REPLACE_ORIG_WITH_UNDERSCORE.to_string(),
+            replace_orig_with_space: REPLACE_ORIG_WITH_SPACE.to_string(),
+            filter_processed_after_last_processed_was_space:
+                FILTER_PROCESSED_AFTER_LAST_PROCESSED_WAS_SPACE.to_string(),
+            filter_processed_after_last_processed_was_underscore:
+                FILTER_PROCESSED_AFTER_LAST_PROCESSED_WAS_UNDERSCORE.to_string(),
+            filter_orig_after_last_processed_was_whitespace:
+                FILTER_ORIG_AFTER_LAST_PROCESSED_WAS_WHITESPACE.to_string(),
+            filter_orig_non_printing_chars: FILTER_ORIG_NON_PRINTING_CHARS.to_string(),
+            trim_line: TRIM_LINE.to_string(),
+            handle_reserved_names: HANDLE_RESERVED_NAMES,
+            insert_line_separator: INSERT_LINE_SEPARATOR,
+            slugify: SLUGIFY,
+            max_len: None,
+        }
+    }
+}
+
+impl Sanitizer {
+    /// Creates a new `Sanitizer` with the default character sets, identical
+    /// to the ones used by the free function [`sanitize()`].
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the set of characters that are replaced with an underscore `_`.
+    pub fn replace_with_underscore(mut self, chars: &str) -> Self {
+        self.replace_orig_with_underscore = chars.to_string();
+        self
+    }
+
+    /// Sets the set of characters that are replaced with a space ` `.
+    pub fn replace_with_space(mut self, chars: &str) -> Self {
+        self.replace_orig_with_space = chars.to_string();
+        self
+    }
+
+    /// Sets the set of characters that are trimmed from the beginning and
+    /// the end of every line, and of the whole output.
+    pub fn trim_chars(mut self, chars: &str) -> Self {
+        self.trim_line = chars.to_string();
+        self
+    }
+
+    /// Sets the character inserted between former lines of the input.
+    pub fn line_separator(mut self, c: char) -> Self {
+        self.insert_line_separator = c;
+        self
+    }
+
+    /// Enables or disables rewriting lines whose stem is a Windows reserved
+    /// device name (`CON`, `PRN`, `AUX`, `NUL`, `COM0`-`COM9`, `LPT0`-`LPT9`).
+    /// Enabled by default. Callers who only ever produce note titles, never
+    /// bare filenames, may want to disable this.
+    pub fn handle_reserved_names(mut self, yes: bool) -> Self {
+        self.handle_reserved_names = yes;
+        self
+    }
+
+    /// Enables or disables ASCII-slug mode. When enabled, the sanitized
+    /// result is additionally lower-cased, accented Latin characters are
+    /// transliterated to ASCII (e.g. `é` to `e`, `ü` to `u`, `ß` to `ss`),
+    /// runs of spaces, underscores and dashes are collapsed into single
+    /// dashes with leading/trailing dashes trimmed, and any other character
+    /// outside `[a-z0-9-]` is dropped. Disabled by default, since the
+    /// crate otherwise intentionally preserves Unicode; enable it when you
+    /// need a clean URL path segment instead of a human-readable title.
+    pub fn slugify(mut self, yes: bool) -> Self {
+        self.slugify = yes;
+        self
+    }
+
+    /// Sets a maximum output length in bytes, e.g. `255` for most file
+    /// systems. When the sanitized result would exceed it, it is truncated
+    /// without splitting a multi-byte UTF-8 sequence or a combining
+    /// sequence. If a short extension-like suffix follows the last `.`, the
+    /// stem is truncated instead, keeping the extension intact. Unset by
+    /// default.
+    pub fn max_len(mut self, n: usize) -> Self {
+        self.max_len = Some(n);
+        self
+    }
+
+    /// Converts strings in a file system friendly and human readable form,
+    /// using this `Sanitizer`'s configuration.
+    pub fn sanitize(&self, s: &str) -> String {
+        // This is used in a closure later.
+        let mut last_processed_chr = LAST_PROCESSED_START_CHAR;
+
+        // Strip a leading BOM and normalize newlines, then proceed line by
+        // line. Lines are joined with `LINE_SEPARATOR_SENTINEL` rather than
+        // `self.insert_line_separator` directly: the sentinel is a control
+        // character, which step 2 above already guarantees never survives
+        // as ordinary line content, so it unambiguously marks real line
+        // boundaries even when a line's own content contains the
+        // (configurable, otherwise ordinary) separator character.
+        let normalized = normalize_newlines(s);
+        let joined = normalized
+            .split('\n')
+            .map(|l| {
+                let s = l
+                    .chars()
+                    // Replace tab with space.
+                    .map(|c| if c.is_whitespace() { ' ' } else { c })
+                    // Delete control characters.
+                    .filter(|c| !c.is_control())
+                    .map(|c_orig| {
+                        // Replace `replace_orig_with_underscore` characters
+                        // with underscore, `replace_orig_with_space` ones with space.
+                        if self.replace_orig_with_underscore.find(c_orig).is_some() {
+                            (c_orig, '_')
+                        } else if self.replace_orig_with_space.find(c_orig).is_some() {
+                            (c_orig, ' ')
+                        } else {
+                            (c_orig, c_orig)
+                        }
+                    })
+                    .filter(|&(c_orig, c)| {
+                        let discard = (self
+                            .filter_processed_after_last_processed_was_space
+                            .find(c)
+                            .is_some()
+                            && last_processed_chr == ' ')
+                            || (self
+                                .filter_processed_after_last_processed_was_underscore
+                                .find(c)
+                                .is_some()
+                                && last_processed_chr == '_')
+                            || (self
+                                .filter_orig_after_last_processed_was_whitespace
+                                .find(c_orig)
+                                .is_some()
+                                && last_processed_chr.is_whitespace())
+                            || self.filter_orig_non_printing_chars.find(c_orig).is_some();
+                        if !discard {
+                            last_processed_chr = c;
+                        };
+                        !discard
+                    })
+                    .map(|(_, c)| c)
+                    .collect::<String>()
+                    // Trim whitespace and `_-.,;` at the beginning and the end of the line.
+                    .trim_matches(|c: char| c.is_whitespace() || self.trim_line.find(c).is_some())
+                    .to_string();
+                s
+            })
+            .collect::<Vec<_>>()
+            // Filter newline and mark the line boundary.
+            .join(&LINE_SEPARATOR_SENTINEL.to_string());
+
+        // Trim whitespace and `_-.,;` at the beginning and the end of the
+        // whole string, treating the sentinel exactly as
+        // `insert_line_separator` would be treated, so empty leading/
+        // trailing lines collapse the same way they did before the
+        // sentinel was introduced.
+        let sep_is_trimmed = self.insert_line_separator.is_whitespace()
+            || self.trim_line.find(self.insert_line_separator).is_some();
+        let joined = joined
+            .trim_matches(|c: char| {
+                if c == LINE_SEPARATOR_SENTINEL {
+                    sep_is_trimmed
+                } else {
+                    c.is_whitespace() || self.trim_line.find(c).is_some()
+                }
+            })
+            .to_string();
+
+        // Recover the real, final per-line strings from the sentinel, and
+        // rewrite lines whose stem is a Windows reserved device name, e.g.
+        // `LPT9.asdf` to `_LPT9.asdf`.
+        let lines = joined.split(LINE_SEPARATOR_SENTINEL).map(|line| {
+            if self.handle_reserved_names
+                && is_reserved_device_name(line.split('.').next().unwrap_or(""))
+            {
+                format!("_{}", line)
+            } else {
+                line.to_string()
+            }
+        });
+        let s = lines
+            .collect::<Vec<_>>()
+            .join(&self.insert_line_separator.to_string());
+
+        let s = if self.slugify {
+            // `to_slug` maps `_` to `-` and trims leading/trailing dashes,
+            // which would strip off the `_` prefix the reserved-name check
+            // above just added. Since slugifying also discards the `.`
+            // extension separator and collapses all lines into one, there
+            // is no per-line stem left to check; re-run the check once on
+            // the whole slug instead, using a `-` prefix so it stays
+            // inside the slug's own `[a-z0-9-]` alphabet and survives.
+            let slug = to_slug(&s);
+            if self.handle_reserved_names && is_reserved_device_name(&slug) {
+                format!("-{}", slug)
+            } else {
+                slug
+            }
+        } else {
+            s
+        };
+
+        match self.max_len {
+            Some(max_len) => apply_max_len(&s, max_len, &self.trim_line),
+            None => s,
+        }
+    }
+
+    /// Like [`Sanitizer::sanitize`], but also returns a map from each output
+    /// `char` to the byte offset in `s` it originated from. Editors that
+    /// derive a filename from a title can use the map to highlight or move
+    /// the cursor to the input range that produced a given output character.
+    ///
+    /// A replaced character keeps the byte offset of the character it
+    /// replaced; a deleted character contributes no map entry; the inserted
+    /// line separator maps to the offset of the newline that it stands in
+    /// for. Trimmed
+    /// characters are dropped from both the output and the map, so the two
+    /// are always the same length.
+    ///
+    /// Slug mode (see [`Sanitizer::slugify`]) is not reflected in the map
+    /// and is ignored by this method.
+    pub fn sanitize_with_map(&self, s: &str) -> (String, Vec<usize>) {
+        let normalized = normalize_newlines_with_map(s);
+        let lines = split_into_lines_with_map(normalized, s.len());
+
+        let mut entries: Vec<(usize, char)> = Vec::new();
+        let line_count = lines.len();
+        for (i, (line, sep_origin)) in lines.into_iter().enumerate() {
+            let mut last_processed_chr = LAST_PROCESSED_START_CHAR;
+            let mut line_entries: Vec<(usize, char)> = Vec::new();
+            for (origin, raw_c) in line {
                 // Replace tab with space.
-                .map(|c| if c.is_whitespace() { ' ' } else { c })
+                let c_orig = if raw_c.is_whitespace() { ' ' } else { raw_c };
                 // Delete control characters.
-                .filter(|c| !c.is_control())
-                .map(|c_orig| {
-                    // Replace `:\\/|?~,;=` with underscore.
-                    if REPLACE_ORIG_WITH_UNDERSCORE.find(c_orig).is_some() {
-                        (c_orig, '_')
-                    } else if REPLACE_ORIG_WITH_SPACE.find(c_orig).is_some() {
-                        (c_orig, ' ')
-                    } else {
-                        (c_orig, c_orig)
-                    }
-                })
-                .filter(|&(c_orig, c)| {
-                    let discard = (FILTER_PROCESSED_AFTER_LAST_PROCESSED_WAS_SPACE
+                if c_orig.is_control() {
+                    continue;
+                }
+                // Replace `replace_orig_with_underscore` characters with
+                // underscore, `replace_orig_with_space` ones with space.
+                let c = if self.replace_orig_with_underscore.find(c_orig).is_some() {
+                    '_'
+                } else if self.replace_orig_with_space.find(c_orig).is_some() {
+                    ' '
+                } else {
+                    c_orig
+                };
+                let discard = (self
+                    .filter_processed_after_last_processed_was_space
+                    .find(c)
+                    .is_some()
+                    && last_processed_chr == ' ')
+                    || (self
+                        .filter_processed_after_last_processed_was_underscore
                         .find(c)
                         .is_some()
-                        && last_processed_chr == ' ')
-                        || (FILTER_PROCESSED_AFTER_LAST_PROCESSED_WAS_UNDERSCORE
-                            .find(c)
-                            .is_some()
-                            && last_processed_chr == '_')
-                        || (FILTER_ORIG_AFTER_LAST_PROCESSED_WAS_WHITESPACE
-                            .find(c_orig)
-                            .is_some()
-                            && last_processed_chr.is_whitespace())
-                        || FILTER_ORIG_NON_PRINTING_CHARS.find(c_orig).is_some();
-                    if !discard {
-                        last_processed_chr = c;
-                    };
-                    !discard
-                })
-                .map(|(_, c)| c)
-                .collect::<String>()
-                // Trim whitespace and `_-.,;` at the beginning and the end of the line.
-                .trim_matches(|c: char| c.is_whitespace() || TRIM_LINE.find(c).is_some())
-                .to_string();
-            // Filter newline and insert line separator `-`.
-            s.push(INSERT_LINE_SEPARATOR);
-            s
-        })
-        .collect::<String>()
-        // Trim whitespace and `_-.,;` at the beginning and the end of the whole string.
-        .trim_matches(|c: char| c.is_whitespace() || TRIM_LINE.find(c).is_some())
-        .to_string()
+                        && last_processed_chr == '_')
+                    || (self
+                        .filter_orig_after_last_processed_was_whitespace
+                        .find(c_orig)
+                        .is_some()
+                        && last_processed_chr.is_whitespace())
+                    || self.filter_orig_non_printing_chars.find(c_orig).is_some();
+                if !discard {
+                    last_processed_chr = c;
+                    line_entries.push((origin, c));
+                }
+            }
+            trim_map_entries(&mut line_entries, &self.trim_line, None);
+            entries.extend(line_entries);
+            if i + 1 < line_count {
+                entries.push((sep_origin, LINE_SEPARATOR_SENTINEL));
+            }
+        }
+        let sep_is_trimmed = self.insert_line_separator.is_whitespace()
+            || self.trim_line.find(self.insert_line_separator).is_some();
+        trim_map_entries(
+            &mut entries,
+            &self.trim_line,
+            Some((LINE_SEPARATOR_SENTINEL, sep_is_trimmed)),
+        );
+
+        let mut entries = if self.handle_reserved_names {
+            apply_reserved_names_entries(entries)
+        } else {
+            entries
+        };
+
+        // Replace sentinel markers with the real, configured line separator
+        // now that real line boundaries are no longer needed.
+        for entry in entries.iter_mut() {
+            if entry.1 == LINE_SEPARATOR_SENTINEL {
+                entry.1 = self.insert_line_separator;
+            }
+        }
+
+        if let Some(max_len) = self.max_len {
+            entries = apply_max_len_entries(entries, max_len, &self.trim_line);
+        }
+
+        let out = entries.iter().map(|&(_, c)| c).collect();
+        let map = entries.iter().map(|&(origin, _)| origin).collect();
+        (out, map)
+    }
+}
+
+/// Converts strings in a file system friendly and human readable form, using
+/// the default character sets. Use [`Sanitizer`] to customize them.
+pub fn sanitize(s: &str) -> String {
+    Sanitizer::default().sanitize(s)
+}
+
+/// Like [`sanitize()`], but also returns a map from each output `char` to
+/// the byte offset in `s` it originated from. See
+/// [`Sanitizer::sanitize_with_map`] for details.
+pub fn sanitize_with_map(s: &str) -> (String, Vec<usize>) {
+    Sanitizer::default().sanitize_with_map(s)
 }
-// TODO
-// Should these be handled?
-// RegexBuilder::new(r#"(?i)^(con|prn|aux|nul|com[0-9]|lpt[0-9])(\..*)?$"#)
 
 #[cfg(test)]
 mod tests {
@@ -164,6 +799,10 @@ mod tests {
         assert_eq!(sanitize("abc\nefg"), "abc-efg".to_string());
         // Test replace Windows newline.
         assert_eq!(sanitize("abc\r\nefg"), "abc-efg".to_string());
+        // Test replace old Mac newline (lone `\r`).
+        assert_eq!(sanitize("abc\refg"), "abc-efg".to_string());
+        // Test strip leading BOM.
+        assert_eq!(sanitize("\u{feff}abc efg"), "abc efg".to_string());
         // Test double '_' or ' '.
         assert_eq!(sanitize("abc_ __  efg __hij"), "abc_ efg hij".to_string());
         // Test hyperlink.
@@ -173,6 +812,189 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_sanitizer_builder() {
+        use super::Sanitizer;
+        // Custom underscore set, additionally replacing `+` and `[]`.
+        assert_eq!(
+            Sanitizer::new()
+                .replace_with_underscore(":\\/|?~+[]")
+                .sanitize("FAT32[1]:name+x"),
+            "FAT32_1_name_x".to_string()
+        );
+        // Custom space set.
+        assert_eq!(
+            Sanitizer::new().replace_with_space("_").sanitize("abc_efg"),
+            "abc efg".to_string()
+        );
+        // Custom line separator.
+        assert_eq!(
+            Sanitizer::new()
+                .line_separator('_')
+                .sanitize("abc\nefg"),
+            "abc_efg".to_string()
+        );
+        // Default `Sanitizer` behaves like the free function `sanitize()`.
+        assert_eq!(
+            Sanitizer::new().sanitize("abc:\\/|?~=efg"),
+            sanitize("abc:\\/|?~=efg")
+        );
+    }
+
+    #[test]
+    fn test_reserved_names() {
+        use super::Sanitizer;
+        // Reserved names are rewritten by default, case-insensitively.
+        assert_eq!(sanitize("LPT9.asdf"), "_LPT9.asdf".to_string());
+        assert_eq!(sanitize("con"), "_con".to_string());
+        assert_eq!(sanitize("Nul.txt"), "_Nul.txt".to_string());
+        // Not a reserved name: extra characters in the stem.
+        assert_eq!(sanitize("console.txt"), "console.txt".to_string());
+        // A literal separator character inside a line must not be mistaken
+        // for a line boundary and split the line into a reserved-name-
+        // looking fragment.
+        assert_eq!(sanitize("con-fig"), "con-fig".to_string());
+        assert_eq!(
+            sanitize("well-nul-documented"),
+            "well-nul-documented".to_string()
+        );
+        // Can be disabled.
+        assert_eq!(
+            Sanitizer::new()
+                .handle_reserved_names(false)
+                .sanitize("LPT9.asdf"),
+            "LPT9.asdf".to_string()
+        );
+    }
+
+    #[test]
+    fn test_slugify() {
+        use super::Sanitizer;
+        // Disabled by default: Unicode is preserved.
+        assert_eq!(sanitize("résumé"), "résumé".to_string());
+        // Opt-in slug mode transliterates and lower-cases.
+        assert_eq!(
+            Sanitizer::new().slugify(true).sanitize("résumé"),
+            "resume".to_string()
+        );
+        assert_eq!(
+            Sanitizer::new().slugify(true).sanitize("Größe.txt"),
+            "grossetxt".to_string()
+        );
+        // Runs of spaces/underscores collapse into a single dash.
+        assert_eq!(
+            Sanitizer::new()
+                .slugify(true)
+                .sanitize("Hello   World__Again"),
+            "hello-world-again".to_string()
+        );
+        // Leading/trailing dashes are trimmed.
+        assert_eq!(
+            Sanitizer::new().slugify(true).sanitize("-Hello-"),
+            "hello".to_string()
+        );
+        // Reserved-name protection (on by default) is not silently undone
+        // by slugification trimming away the escape prefix.
+        assert_eq!(
+            Sanitizer::new().slugify(true).sanitize("con"),
+            "-con".to_string()
+        );
+        assert_eq!(
+            Sanitizer::new().slugify(true).sanitize("NUL"),
+            "-nul".to_string()
+        );
+        // Can be disabled together with reserved-name handling.
+        assert_eq!(
+            Sanitizer::new()
+                .slugify(true)
+                .handle_reserved_names(false)
+                .sanitize("con"),
+            "con".to_string()
+        );
+    }
+
+    #[test]
+    fn test_max_len() {
+        use super::Sanitizer;
+        // No truncation when already within the limit.
+        assert_eq!(
+            Sanitizer::new().max_len(20).sanitize("short"),
+            "short".to_string()
+        );
+        // Extension is preserved, stem is truncated and re-trimmed.
+        let title = "a".repeat(300);
+        let input = format!("{}.md", title);
+        let output = Sanitizer::new().max_len(255).sanitize(&input);
+        assert!(output.len() <= 255);
+        assert!(output.ends_with(".md"));
+        // No extension: the whole string is truncated.
+        let output = Sanitizer::new().max_len(10).sanitize(&title);
+        assert_eq!(output, "aaaaaaaaaa".to_string());
+        // Truncation never leaves a trailing `_-.,;` or whitespace.
+        assert_eq!(
+            Sanitizer::new().max_len(8).sanitize("abc def_ghi"),
+            "abc def".to_string()
+        );
+        // A multi-byte character is never split.
+        let emoji_title = "é".repeat(10);
+        let output = Sanitizer::new().max_len(5).sanitize(&emoji_title);
+        assert!(output.len() <= 5);
+        assert!(output.chars().all(|c| c == 'é'));
+    }
+
+    #[test]
+    fn test_sanitize_with_map() {
+        use super::sanitize_with_map;
+
+        // Output and map always have the same length, and the output text
+        // matches the plain `sanitize()` result.
+        for input in [
+            "abc:\\/|?~=efg",
+            "abc\nefg",
+            "abc\r\nefg",
+            "  abc_ __  efg __hij  ",
+            "LPT9.asdf",
+            "résumé",
+            "con-fig",
+            "well-nul-documented",
+        ] {
+            let (out, map) = sanitize_with_map(input);
+            assert_eq!(out.chars().count(), map.len());
+            assert_eq!(out, super::sanitize(input));
+        }
+
+        // Every mapped offset points at the start of a char boundary of a
+        // byte sequence in the input that actually produced the output char
+        // at that position (for non-synthetic chars, i.e. everything except
+        // an inserted line separator or a reserved-name prefix).
+        let (out, map) = sanitize_with_map("abc:efg");
+        assert_eq!(out, "abc_efg");
+        assert_eq!(map, vec![0, 1, 2, 3, 4, 5, 6]);
+
+        // The inserted line separator maps to the newline's offset.
+        let (out, map) = sanitize_with_map("abc\nefg");
+        assert_eq!(out, "abc-efg");
+        assert_eq!(map, vec![0, 1, 2, 3, 4, 5, 6]);
+
+        // A replaced character keeps the offset of the character it replaced.
+        let (out, map) = sanitize_with_map("a:b");
+        assert_eq!(out, "a_b");
+        assert_eq!(map, vec![0, 1, 2]);
+
+        // A deleted character (here, a control character) contributes no
+        // map entry.
+        let (out, map) = sanitize_with_map("a\u{0019}b");
+        assert_eq!(out, "ab");
+        assert_eq!(map, vec![0, 2]);
+
+        // A literal separator character inside a single line is not
+        // mistaken for a line boundary and must not trigger a spurious
+        // reserved-name rewrite.
+        let (out, map) = sanitize_with_map("con-fig");
+        assert_eq!(out, "con-fig");
+        assert_eq!(map, vec![0, 1, 2, 3, 4, 5, 6]);
+    }
+
     // File stem examples are taken from:
     // https://github.com/parshap/node-sanitize-filename/blob/master/test.js
     // (the extension is usually added after sanitzing the file stem.)
@@ -285,7 +1107,7 @@ mod tests {
         "foobar",
         "foobar",
         "what",
-        "LPT9.asdf",
+        "_LPT9.asdf",
         "author_ title",
         "author _ title",
         "author_ title",
@@ -308,3 +1130,4 @@ mod tests {
         }
     }
 }
+